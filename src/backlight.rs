@@ -0,0 +1,46 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use discovery;
+
+const BACKLIGHT_CLASS: &str = "backlight";
+
+const BRIGHTNESS_ATTR: &str = "brightness";
+const MAX_BRIGHTNESS_ATTR: &str = "max_brightness";
+
+/// Finds the first node under `/sys/class/backlight` that exposes
+/// `max_brightness`, rather than assuming `intel_backlight`.
+pub fn discover_screen_backlight() -> io::Result<PathBuf> {
+    discovery::find(BACKLIGHT_CLASS, "screen", discovery::has_attr(MAX_BRIGHTNESS_ATTR))
+}
+
+/// Reads the current screen backlight level, as a percentage of
+/// `max_brightness`.
+pub fn get_screen_brightness() -> io::Result<u8> {
+    let backlight = discover_screen_backlight()?;
+
+    let max = read_u32(&backlight.join(MAX_BRIGHTNESS_ATTR))?;
+    let current = read_u32(&backlight.join(BRIGHTNESS_ATTR))?;
+
+    Ok((current.saturating_mul(100) / max.max(1)).min(100) as u8)
+}
+
+/// Sets the screen backlight to `percent` of `max_brightness`, clamped to
+/// `0..=100` first.
+pub fn set_screen_brightness(percent: u8) -> io::Result<()> {
+    let backlight = discover_screen_backlight()?;
+
+    let max = read_u32(&backlight.join(MAX_BRIGHTNESS_ATTR))?;
+    let value = max * u32::from(percent.min(100)) / 100;
+
+    fs::write(backlight.join(BRIGHTNESS_ATTR), value.to_string())
+}
+
+fn read_u32(attr: &Path) -> io::Result<u32> {
+    let value = fs::read_to_string(attr)?;
+    value.trim().parse::<u32>().map_err(|why| io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid value in {}: {}", attr.display(), why)
+    ))
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use discovery;
+
+const POWER_SUPPLY_CLASS: &str = "power_supply";
+
+const START_THRESHOLD_ATTR: &str = "charge_control_start_threshold";
+const END_THRESHOLD_ATTR: &str = "charge_control_end_threshold";
+
+/// Finds the first `power_supply` node of `type` `Battery` that also exposes
+/// `charge_control_end_threshold`, which is what the kernel uses to signal
+/// charge-limiting support, rather than assuming the node is named `BAT0`.
+pub fn discover_battery() -> io::Result<PathBuf> {
+    discovery::find(POWER_SUPPLY_CLASS, "battery", |path| {
+        discovery::attr_equals("type", "Battery")(path) && discovery::has_attr(END_THRESHOLD_ATTR)(path)
+    })
+}
+
+/// Reads the currently-applied start/end charge thresholds, in percent.
+///
+/// `start` is `0` when the battery does not support a start threshold.
+pub fn get_charge_thresholds() -> io::Result<(u8, u8)> {
+    let battery = discover_battery()?;
+
+    let end = read_percent(&battery.join(END_THRESHOLD_ATTR))?;
+
+    let start = if battery.join(START_THRESHOLD_ATTR).exists() {
+        read_percent(&battery.join(START_THRESHOLD_ATTR))?
+    } else {
+        0
+    };
+
+    Ok((start, end))
+}
+
+/// Writes the given start/end charge thresholds to sysfs, clamping to
+/// `0..=100` and enforcing `start < end` first.
+///
+/// Falls back to writing only `end` when the battery does not expose a
+/// start-threshold attribute.
+pub fn set_charge_thresholds(start: u8, end: u8) -> io::Result<()> {
+    let end = end.min(100).max(1);
+    let start = start.min(end.saturating_sub(1));
+
+    let battery = discover_battery()?;
+    let start_attr = battery.join(START_THRESHOLD_ATTR);
+    let end_attr = battery.join(END_THRESHOLD_ATTR);
+
+    if start_attr.exists() {
+        // The end threshold must never be left lower than the start
+        // threshold while both are being updated, so raise it first.
+        fs::write(&end_attr, "100")?;
+        fs::write(&start_attr, start.to_string())?;
+        fs::write(&end_attr, end.to_string())?;
+    } else {
+        warn!("battery does not support a start threshold; only end_threshold will be set");
+        fs::write(&end_attr, end.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn read_percent(attr: &Path) -> io::Result<u8> {
+    let value = fs::read_to_string(attr)?;
+    value.trim().parse::<u8>().map_err(|why| io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid value in {}: {}", attr.display(), why)
+    ))
+}
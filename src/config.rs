@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
@@ -7,6 +9,21 @@ use toml;
 const CONFIG_PARENT: &str = "/etc/system76-power/";
 const CONFIG_PATH: &str = "/etc/system76-power/config.toml";
 
+/// A single issue found by `Config::validate`, naming the TOML location it
+/// was found at (e.g. `[profiles.performance].pstate`) alongside a
+/// human-readable explanation.
+#[derive(Clone, Debug)]
+pub struct ConfigProblem {
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default)]
@@ -14,6 +31,8 @@ pub struct Config {
     #[serde(default)]
     pub thresholds: ConfigThresholds,
     #[serde(default)]
+    pub charge: ConfigCharge,
+    #[serde(default)]
     pub profiles: ConfigProfiles,
 }
 
@@ -22,6 +41,7 @@ impl Default for Config {
         Self {
             defaults: Default::default(),
             thresholds: Default::default(),
+            charge: Default::default(),
             profiles: Default::default(),
         }
     }
@@ -30,9 +50,29 @@ impl Default for Config {
 impl Config {
     /// Attempts to get the current configuration from the `CONFIG_PATH`.
     ///
-    /// If an error occurs, the default config will be used instead, which will
-    /// allow the daemon to continue operating with the recommended defaults.
+    /// If the file is missing, invalid, or fails validation, the default
+    /// config is used instead, so the daemon can keep operating. Every
+    /// problem found is still logged at `error` level with its TOML
+    /// location, rather than being swallowed; callers that can surface the
+    /// failure more directly to the user (the `config` subcommand) should
+    /// use `new_checked` instead so they can report it and exit non-zero.
     pub fn new() -> Config {
+        match Config::new_checked() {
+            Ok(config) => config,
+            Err(problems) => {
+                for problem in &problems {
+                    error!("invalid config (defaults will be used, instead): {}", problem);
+                }
+
+                Config::default()
+            }
+        }
+    }
+
+    /// Like `new`, but returns every validation problem instead of logging
+    /// it and substituting defaults, so a caller such as the `config` subcommand
+    /// can print them and exit non-zero.
+    pub fn new_checked() -> Result<Config, Vec<ConfigProblem>> {
         let config_path = &Path::new(CONFIG_PATH);
         if ! config_path.exists() {
             info!("config file does not exist at {}; creating it", CONFIG_PATH);
@@ -41,16 +81,10 @@ impl Config {
                 error!("failed to write config to file system: {}", why);
             }
 
-            config
-        } else {
-            match Config::read() {
-                Ok(config) => config,
-                Err(why) => {
-                    error!("failed to read config file (defaults will be used, instead): {}", why);
-                    Config::default()
-                }
-            }
+            return Ok(config);
         }
+
+        Config::read()
     }
 
     /// Update the config at the `CONFIG_PATH`.
@@ -68,17 +102,105 @@ impl Config {
         Ok(())
     }
 
-    /// Attempt to read the configuration file at the `CONFIG_PATH`.
-    fn read() -> io::Result<Config> {
+    /// Attempt to read and validate the configuration file at
+    /// `CONFIG_PATH`, returning every problem found rather than stopping
+    /// at the first one.
+    fn read() -> Result<Config, Vec<ConfigProblem>> {
+        let problem = |location: &str, why: &dyn fmt::Display| vec![ConfigProblem {
+            location: location.to_string(),
+            message: why.to_string(),
+        }];
+
         let config_path = &Path::new(CONFIG_PATH);
-        let mut file = File::open(config_path)?;
+        let mut file = File::open(config_path).map_err(|why| problem("[file]", &why))?;
         let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+        file.read_to_end(&mut buffer).map_err(|why| problem("[file]", &why))?;
+
+        let config: Config = toml::from_slice(&buffer).map_err(|why| problem("[toml]", &why))?;
+
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(problems);
+        }
 
-        toml::from_slice(&buffer).map_err(|why| io::Error::new(
-            io::ErrorKind::Other,
-            format!("failed to deserialize config: {}", why)
-        ))
+        Ok(config)
+    }
+
+    /// Checks the config for problems that deserialization alone can't
+    /// catch, returning one `ConfigProblem` per issue found, each naming
+    /// its TOML location. An empty vec means the config is sound.
+    ///
+    /// This is what backs both the `config` subcommand's validation report
+    /// and the defaults-fallback check in `read()`.
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+
+        for (field, profile) in &[
+            ("defaults.battery", &self.defaults.battery),
+            ("defaults.ac", &self.defaults.ac),
+            ("defaults.last_profile", &self.defaults.last_profile),
+        ] {
+            if !self.profiles.0.contains_key(profile.as_str()) {
+                problems.push(ConfigProblem {
+                    location: format!("[{}]", field),
+                    message: format!("'{}' is not a defined profile", profile.as_str()),
+                });
+            }
+        }
+
+        if self.thresholds.critical >= self.thresholds.normal {
+            problems.push(ConfigProblem {
+                location: "[threshold]".to_string(),
+                message: format!(
+                    "critical ({}) must be lower than normal ({})",
+                    self.thresholds.critical, self.thresholds.normal
+                ),
+            });
+        }
+
+        self.charge.validate("[charge]", &mut problems);
+
+        for (name, profile) in &self.profiles.0 {
+            let location = format!("[profiles.{}]", name);
+
+            if let Some(ref pstate) = profile.pstate {
+                if pstate.min > pstate.max {
+                    problems.push(ConfigProblem {
+                        location: format!("{}.pstate", location),
+                        message: format!("min ({}) is greater than max ({})", pstate.min, pstate.max),
+                    });
+                }
+
+                if pstate.max > 100 {
+                    problems.push(ConfigProblem {
+                        location: format!("{}.pstate", location),
+                        message: format!("max ({}) exceeds 100", pstate.max),
+                    });
+                }
+            }
+
+            if let Some(ref backlight) = profile.backlight {
+                if backlight.keyboard > 100 {
+                    problems.push(ConfigProblem {
+                        location: format!("{}.backlight", location),
+                        message: format!("keyboard ({}) exceeds 100", backlight.keyboard),
+                    });
+                }
+
+                if backlight.screen > 100 {
+                    problems.push(ConfigProblem {
+                        location: format!("{}.backlight", location),
+                        message: format!("screen ({}) exceeds 100", backlight.screen),
+                    });
+                }
+            }
+
+            if let Some(ref charge) = profile.charge {
+                charge.validate(&format!("{}.charge", location), &mut problems);
+            }
+        }
+
+        problems
     }
 
     /// Custom serialization to a more readable format.
@@ -89,6 +211,7 @@ impl Config {
             out.extend_from_slice(b"# This config is automatically generated by system76-power.\n\n");
             self.defaults.serialize_toml(out);
             self.thresholds.serialize_toml(out);
+            self.charge.serialize_toml(out);
             self.profiles.serialize_toml(out);
         }
         out
@@ -129,9 +252,9 @@ impl ConfigDefaults {
             ac = '{}'\n\
             # The last profile that was activated\n\
             last_profile = '{}'",
-                <&'static str>::from(self.battery),
-                <&'static str>::from(self.ac),
-                <&'static str>::from(self.last_profile)
+                self.battery.as_str(),
+                self.ac.as_str(),
+                self.last_profile.as_str()
         );
 
         let exp: &[u8] = if self.experimental {
@@ -164,7 +287,7 @@ impl ConfigThresholds {
             out,
             "[threshold]\n\
             # Defines what percentage of battery is required to set the profile to 'battery'.\n\
-            crtical = {}\n\
+            critical = {}\n\
             # Defines what percentage of battery is required to revert the critical change.\n\
             normal = {}\n",
             self.critical,
@@ -173,36 +296,99 @@ impl ConfigThresholds {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct ConfigProfiles {
-    #[serde(default = "ConfigProfile::battery")]
-    pub battery: ConfigProfile,
-    #[serde(default = "ConfigProfile::balanced")]
-    pub balanced: ConfigProfile,
-    #[serde(default = "ConfigProfile::performance")]
-    pub performance: ConfigProfile
+/// Caps the charge level of the battery, independently of the active power
+/// profile, to reduce long-term wear.
+///
+/// `start_threshold` is clamped to the `0..end_threshold` range and is
+/// dropped, with a warning logged, when applying to hardware that only
+/// exposes `charge_control_end_threshold`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ConfigCharge {
+    pub start_threshold: u8,
+    pub end_threshold: u8,
 }
 
-impl Default for ConfigProfiles {
+impl Default for ConfigCharge {
     fn default() -> Self {
-        Self {
-            battery: ConfigProfile::battery(),
-            balanced: ConfigProfile::balanced(),
-            performance: ConfigProfile::performance()
+        Self { start_threshold: 0, end_threshold: 100 }
+    }
+}
+
+impl ConfigCharge {
+    /// Clamps `start_threshold` and `end_threshold` to the `0..=100` range,
+    /// additionally forcing `start_threshold < end_threshold`.
+    pub fn clamped(self) -> Self {
+        let end = self.end_threshold.min(100).max(1);
+        let start = self.start_threshold.min(end.saturating_sub(1));
+        Self { start_threshold: start, end_threshold: end }
+    }
+
+    fn validate(&self, location: &str, problems: &mut Vec<ConfigProblem>) {
+        if self.end_threshold > 100 {
+            problems.push(ConfigProblem {
+                location: location.to_string(),
+                message: format!("end_threshold ({}) exceeds 100", self.end_threshold),
+            });
+        }
+
+        if self.start_threshold >= self.end_threshold {
+            problems.push(ConfigProblem {
+                location: location.to_string(),
+                message: format!(
+                    "start_threshold ({}) must be lower than end_threshold ({})",
+                    self.start_threshold, self.end_threshold
+                ),
+            });
         }
     }
+
+    fn serialize_toml(&self, out: &mut Vec<u8>) {
+        let _ = writeln!(
+            out,
+            "[charge]\n\
+            # The battery charge percentage to resume charging at.\n\
+            start_threshold = {}\n\
+            # The battery charge percentage to stop charging at.\n\
+            end_threshold = {}\n",
+            self.start_threshold,
+            self.end_threshold
+        );
+    }
+}
+
+/// A user-extensible set of named profiles, keyed by profile name.
+///
+/// The three built-in names (`battery`, `balanced`, `performance`) are
+/// always present in the default config, but users may add their own
+/// (e.g. `[profiles.quiet]`) and reference them from `[defaults]` or the
+/// `profile` subcommand just like the built-ins.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConfigProfiles(pub BTreeMap<String, ConfigProfile>);
+
+impl Default for ConfigProfiles {
+    fn default() -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert("battery".to_string(), ConfigProfile::battery());
+        profiles.insert("balanced".to_string(), ConfigProfile::balanced());
+        profiles.insert("performance".to_string(), ConfigProfile::performance());
+        Self(profiles)
+    }
 }
 
 impl ConfigProfiles {
-    pub fn serialize_toml(&self, out: &mut Vec<u8>) {
-        out.extend_from_slice(b"[profiles.battery]\n");
-        self.battery.serialize_toml(out);
+    pub fn get(&self, name: &str) -> Option<&ConfigProfile> {
+        self.0.get(name)
+    }
 
-        out.extend_from_slice(b"[profiles.balanced]\n");
-        self.balanced.serialize_toml(out);
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
 
-        out.extend_from_slice(b"[profiles.performance]\n");
-        self.performance.serialize_toml(out);
+    pub fn serialize_toml(&self, out: &mut Vec<u8>) {
+        for (name, profile) in &self.0 {
+            let _ = writeln!(out, "[profiles.{}]", name);
+            profile.serialize_toml(out);
+        }
     }
 }
 
@@ -210,7 +396,15 @@ impl ConfigProfiles {
 pub struct ConfigProfile {
     pub backlight: Option<ConfigBacklight>,
     pub pstate: Option<ConfigPState>,
-    pub script: Option<PathBuf>,
+    /// Overrides the `[charge]` thresholds while this profile is active.
+    pub charge: Option<ConfigCharge>,
+    /// Run when this profile becomes active. `script` is accepted as an
+    /// alias so existing configs keep working.
+    #[serde(alias = "script")]
+    pub on_load: Option<PathBuf>,
+    /// Run when switching away from this profile, before `on_load` of the
+    /// new one, so it can undo any side effects `on_load` caused.
+    pub on_unload: Option<PathBuf>,
 }
 
 impl ConfigProfile {
@@ -218,7 +412,9 @@ impl ConfigProfile {
         Self {
             backlight: Some(ConfigBacklight::battery()),
             pstate: Some(ConfigPState::battery()),
-            script: None
+            charge: None,
+            on_load: None,
+            on_unload: None
         }
     }
 
@@ -226,7 +422,9 @@ impl ConfigProfile {
         Self {
             backlight: Some(ConfigBacklight::balanced()),
             pstate: Some(ConfigPState::balanced()),
-            script: None
+            charge: None,
+            on_load: None,
+            on_unload: None
         }
     }
 
@@ -234,7 +432,9 @@ impl ConfigProfile {
         Self {
             backlight: Some(ConfigBacklight::performance()),
             pstate: Some(ConfigPState::performance()),
-            script: None
+            charge: None,
+            on_load: None,
+            on_unload: None
         }
     }
 
@@ -247,9 +447,23 @@ impl ConfigProfile {
             pstate.serialize_toml(out);
         }
 
-        let _ = match self.script {
-            Some(ref script) => writeln!(out, "battery = '{}'", script.display()),
-            None => writeln!(out, "# script = '$PATH'")
+        if let Some(ref charge) = self.charge {
+            let _ = writeln!(
+                out,
+                "charge = {{ start_threshold = {}, end_threshold = {} }}",
+                charge.start_threshold,
+                charge.end_threshold
+            );
+        }
+
+        let _ = match self.on_load {
+            Some(ref script) => writeln!(out, "on_load = '{}'", script.display()),
+            None => writeln!(out, "# on_load = '$PATH'")
+        };
+
+        let _ = match self.on_unload {
+            Some(ref script) => writeln!(out, "on_unload = '{}'", script.display()),
+            None => writeln!(out, "# on_unload = '$PATH'")
         };
 
         out.push(b'\n');
@@ -259,24 +473,76 @@ impl ConfigProfile {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ConfigBacklight {
     pub keyboard: u8,
-    pub screen: u8
+    pub screen: u8,
+    /// The keyboard LED color(s) to apply alongside `keyboard` brightness.
+    /// Keyboards exposing neither `multi_intensity` nor `color` ignore this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyboard_color: Option<KeyboardColor>,
 }
 
 impl ConfigBacklight {
     fn battery() -> Self {
-        Self { keyboard: 0, screen: 10 }
+        Self { keyboard: 0, screen: 10, keyboard_color: None }
     }
 
     fn balanced() -> Self {
-        Self { keyboard: 50, screen: 40 }
+        Self { keyboard: 50, screen: 40, keyboard_color: None }
     }
 
     fn performance() -> Self {
-        Self { keyboard: 100, screen: 100 }
+        Self { keyboard: 100, screen: 100, keyboard_color: None }
     }
 
     fn serialize_toml(&self, out: &mut Vec<u8>) {
-        let _ = writeln!(out, "backlight = {{ keyboard = {}, screen = {} }}", self.keyboard, self.screen);
+        // `keyboard_color` must be written as a key inside this same
+        // inline table: it is a field of `ConfigBacklight`, so writing it
+        // as a sibling key under `[profiles.<name>]` would deserialize
+        // into nothing (there is no such top-level key) and silently
+        // drop the color on the next read-modify-write cycle.
+        match self.keyboard_color {
+            Some(ref color) => {
+                let _ = writeln!(
+                    out,
+                    "backlight = {{ keyboard = {}, screen = {}, keyboard_color = {} }}",
+                    self.keyboard, self.screen, color.toml_value()
+                );
+            }
+            None => {
+                let _ = writeln!(out, "backlight = {{ keyboard = {}, screen = {} }}", self.keyboard, self.screen);
+            }
+        }
+    }
+
+    /// Clamps `keyboard`/`screen` to `0..=100`.
+    pub fn clamped(self) -> Self {
+        Self {
+            keyboard: self.keyboard.min(100),
+            screen: self.screen.min(100),
+            keyboard_color: self.keyboard_color,
+        }
+    }
+}
+
+/// A keyboard LED color, as either a single hex color applied to every
+/// zone, or a list of hex colors, one per zone.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KeyboardColor {
+    Single(String),
+    PerZone(Vec<String>),
+}
+
+impl KeyboardColor {
+    /// Renders as a TOML value suitable for embedding inside an inline
+    /// table, e.g. `'ff0000'` or `['ff0000', '00ff00']`.
+    fn toml_value(&self) -> String {
+        match *self {
+            KeyboardColor::Single(ref color) => format!("'{}'", color),
+            KeyboardColor::PerZone(ref colors) => format!(
+                "[{}]",
+                colors.iter().map(|color| format!("'{}'", color)).collect::<Vec<_>>().join(", ")
+            ),
+        }
     }
 }
 
@@ -303,34 +569,115 @@ impl ConfigPState {
     fn serialize_toml(&self, out: &mut Vec<u8>) {
         let _ = writeln!(out, "pstate = {{ min = {}, max = {}, turbo = {} }}", self.min, self.max, self.turbo);
     }
-}
-
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
-pub enum Profile {
-    #[serde(rename = "battery")]
-    Battery,
-    #[serde(rename = "balanced")]
-    Balanced,
-    #[serde(rename = "performance")]
-    Performance
-}
 
-impl From<Profile> for &'static str {
-    fn from(profile: Profile) -> Self {
-        match profile {
-            Profile::Balanced => "balanced",
-            Profile::Battery => "battery",
-            Profile::Performance => "performance"
-        }
+    /// Clamps `min`/`max` to `0..=100`, additionally forcing `min <= max`.
+    pub fn clamped(self) -> Self {
+        let min = self.min.min(100);
+        let max = self.max.min(100).max(min);
+        Self { min, max, turbo: self.turbo }
     }
 }
 
+/// The name of a user-defined profile from `[profiles]`.
+///
+/// This used to be a closed three-variant enum; it is now a validated
+/// newtype so that config files can define arbitrary profiles (see
+/// `ConfigProfiles`) without a recompile. `Config::validate` is what
+/// actually checks the name refers to a defined profile.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Profile(pub String);
+
 impl Profile {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     fn ac_default() -> Profile {
-        Profile::Performance
+        Profile("performance".to_string())
     }
 
     fn battery_default() -> Profile {
-        Profile::Balanced
+        Profile("balanced".to_string())
+    }
+}
+
+/// CLI-supplied scalar overrides, layered onto a loaded `Config`'s profile
+/// before it is applied (e.g. `profile performance --pstate-max 90`).
+///
+/// Every field is optional; an unset field leaves whatever the config file
+/// (or the built-in default) already set untouched. Mirrors the
+/// args-struct/config-merge pattern: parse CLI args into this struct, then
+/// call `apply` to merge it over the `ConfigProfile` the daemon is about to
+/// activate.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigOverrides {
+    pub pstate_min: Option<u8>,
+    pub pstate_max: Option<u8>,
+    pub pstate_turbo: Option<bool>,
+    pub keyboard_brightness: Option<u8>,
+    pub screen_brightness: Option<u8>,
+    pub charge_start: Option<u8>,
+    pub charge_end: Option<u8>,
+}
+
+impl ConfigOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.pstate_min.is_none()
+            && self.pstate_max.is_none()
+            && self.pstate_turbo.is_none()
+            && self.keyboard_brightness.is_none()
+            && self.screen_brightness.is_none()
+            && self.charge_start.is_none()
+            && self.charge_end.is_none()
+    }
+
+    /// Merges these overrides onto `profile`, creating its pstate/backlight/
+    /// charge sections if they weren't already defined. Each section is
+    /// clamped to its valid range afterwards, so e.g. `--pstate-max 200`
+    /// can't bypass the same out-of-range checks `Config::validate` enforces.
+    pub fn apply(&self, profile: &mut ConfigProfile) {
+        if self.pstate_min.is_some() || self.pstate_max.is_some() || self.pstate_turbo.is_some() {
+            let pstate = profile.pstate.get_or_insert_with(|| ConfigPState { min: 0, max: 100, turbo: true });
+
+            if let Some(min) = self.pstate_min {
+                pstate.min = min;
+            }
+            if let Some(max) = self.pstate_max {
+                pstate.max = max;
+            }
+            if let Some(turbo) = self.pstate_turbo {
+                pstate.turbo = turbo;
+            }
+
+            *pstate = pstate.clone().clamped();
+        }
+
+        if self.keyboard_brightness.is_some() || self.screen_brightness.is_some() {
+            let backlight = profile.backlight.get_or_insert_with(|| {
+                ConfigBacklight { keyboard: 0, screen: 100, keyboard_color: None }
+            });
+
+            if let Some(keyboard) = self.keyboard_brightness {
+                backlight.keyboard = keyboard;
+            }
+            if let Some(screen) = self.screen_brightness {
+                backlight.screen = screen;
+            }
+
+            *backlight = backlight.clone().clamped();
+        }
+
+        if self.charge_start.is_some() || self.charge_end.is_some() {
+            let charge = profile.charge.get_or_insert_with(ConfigCharge::default);
+
+            if let Some(start) = self.charge_start {
+                charge.start_threshold = start;
+            }
+            if let Some(end) = self.charge_end {
+                charge.end_threshold = end;
+            }
+
+            *charge = charge.clamped();
+        }
     }
 }
\ No newline at end of file
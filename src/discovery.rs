@@ -0,0 +1,82 @@
+//! Finds sysfs nodes by attribute rather than by a hardcoded path.
+//!
+//! Fixed paths like `BAT0` or `intel_backlight` break whenever a machine's
+//! kernel enumerates devices in a different order, or under a different
+//! name. Searching a sysfs class (`backlight`, `power_supply`, `leds`,
+//! `hwmon`, ...) for the node whose *attributes* match what the caller
+//! actually needs is what makes the daemon self-configure across hardware
+//! revisions. `charge`, `keyboard_led`, `fan`, and `backlight` are all built
+//! on top of this.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SYSFS_CLASS: &str = "/sys/class";
+
+lazy_static! {
+    static ref CACHE: Mutex<BTreeMap<(String, String), PathBuf>> = Mutex::new(BTreeMap::new());
+}
+
+/// Finds the first node under `/sys/class/<class>` for which `predicate`
+/// returns true, caching the result under `cache_key` so repeated lookups
+/// (e.g. on every profile switch) don't re-scan the class every time.
+///
+/// The cached path is re-validated with `Path::exists` before being
+/// returned, so a node that disappears (device unplugged, module unloaded)
+/// triggers a fresh search instead of returning a stale path.
+pub fn find<P>(class: &str, cache_key: &str, mut predicate: P) -> io::Result<PathBuf>
+where
+    P: FnMut(&Path) -> bool,
+{
+    let key = (class.to_string(), cache_key.to_string());
+
+    if let Some(cached) = CACHE.lock().unwrap().get(&key) {
+        if cached.exists() {
+            return Ok(cached.clone());
+        }
+    }
+
+    let class_path = Path::new(SYSFS_CLASS).join(class);
+
+    for entry in fs::read_dir(&class_path)? {
+        let path = entry?.path();
+
+        if predicate(&path) {
+            CACHE.lock().unwrap().insert(key, path.clone());
+            return Ok(path);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no node under {} matched '{}'", class_path.display(), cache_key)
+    ))
+}
+
+/// A predicate matching nodes that have the given attribute file at all.
+pub fn has_attr(attr: &'static str) -> impl Fn(&Path) -> bool {
+    move |path| path.join(attr).exists()
+}
+
+/// A predicate matching nodes whose attribute file's trimmed contents equal
+/// `value` exactly (e.g. `type` == `Battery`, `name` == `system76`).
+pub fn attr_equals(attr: &'static str, value: &'static str) -> impl Fn(&Path) -> bool {
+    move |path| {
+        fs::read_to_string(path.join(attr))
+            .map(|contents| contents.trim() == value)
+            .unwrap_or(false)
+    }
+}
+
+/// A predicate matching nodes whose file name ends with `suffix` (e.g.
+/// `:kbd_backlight` for keyboard LED nodes).
+pub fn name_ends_with(suffix: &'static str) -> impl Fn(&Path) -> bool {
+    move |path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.ends_with(suffix))
+    }
+}
@@ -0,0 +1,21 @@
+//! Sysfs lookup for the EC/hwmon fan controller.
+//!
+//! This snapshot does not carry the fan-curve engine that
+//! `"fan-curve"`/`set_fan_curve` ultimately drive (see `daemon::daemon()`);
+//! only the discovery half lives here, so that engine can find its
+//! controller without assuming a fixed `hwmon` enumeration order.
+
+use std::io;
+use std::path::PathBuf;
+
+use discovery;
+
+const HWMON_CLASS: &str = "hwmon";
+
+const PWM_ATTR: &str = "pwm1";
+
+/// Finds the first `hwmon` node exposing `pwm1`, i.e. one an EC fan curve
+/// can actually drive, rather than assuming a fixed `hwmon` index.
+pub fn discover_fan_controller() -> io::Result<PathBuf> {
+    discovery::find(HWMON_CLASS, "fan", discovery::has_attr(PWM_ATTR))
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use discovery;
+
+const LEDS_CLASS: &str = "leds";
+const SUFFIX: &str = ":kbd_backlight";
+
+const MULTI_INTENSITY_ATTR: &str = "multi_intensity";
+const COLOR_ATTR: &str = "color";
+
+/// Finds the keyboard LED node under `/sys/class/leds`, e.g.
+/// `/sys/class/leds/system76::kbd_backlight`.
+pub fn discover_keyboard_led() -> io::Result<PathBuf> {
+    discovery::find(LEDS_CLASS, "keyboard", discovery::name_ends_with(SUFFIX))
+}
+
+/// The number of independently-addressable zones. `multi_intensity` holds
+/// one `r g b` triplet per zone, so this is a third of its whitespace-
+/// separated token count. Single-color and monochrome keyboards, which have
+/// no `multi_intensity` attribute, report 0.
+pub fn zone_count(led: &Path) -> usize {
+    fs::read_to_string(led.join(MULTI_INTENSITY_ATTR))
+        .map(|contents| contents.split_whitespace().count() / 3)
+        .unwrap_or(0)
+}
+
+/// Reads the current per-zone colors as `(r, g, b)` tuples, from whichever
+/// attribute `set_keyboard_color` would have written: `multi_intensity`
+/// when the keyboard has addressable zones, `color` otherwise.
+pub fn get_keyboard_color() -> io::Result<Vec<(u8, u8, u8)>> {
+    let led = discover_keyboard_led()?;
+    let zones = zone_count(&led);
+
+    if zones == 0 {
+        let contents = fs::read_to_string(led.join(COLOR_ATTR))?;
+        let color = parse_hex(contents.trim())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected color attribute format"))?;
+
+        return Ok(vec![color]);
+    }
+
+    let contents = fs::read_to_string(led.join(MULTI_INTENSITY_ATTR))?;
+    let channels: Vec<u8> = contents.split_whitespace()
+        .map(|channel| channel.parse().map_err(|why| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected multi_intensity format: {}", why)
+        )))
+        .collect::<io::Result<_>>()?;
+
+    if channels.len() != zones * 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected {} multi_intensity channels for {} zones, found {}", zones * 3, zones, channels.len())
+        ));
+    }
+
+    Ok(channels.chunks(3).map(|c| (c[0], c[1], c[2])).collect())
+}
+
+/// Writes one color per zone to `multi_intensity`, and the first zone's
+/// color to `color` so both attributes stay in sync. A single color is
+/// broadcast to every zone; a keyboard with no `multi_intensity` zones
+/// writes directly to `color` instead, and one with no `color` attribute
+/// either (single-color or monochrome) is left untouched.
+pub fn set_keyboard_color(colors: &[(u8, u8, u8)]) -> io::Result<()> {
+    let led = discover_keyboard_led()?;
+    let zones = zone_count(&led);
+
+    let color = *colors.first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no colors given"))?;
+
+    if zones == 0 {
+        if !led.join(COLOR_ATTR).exists() {
+            debug!("{}: no color attribute; leaving it untouched", led.display());
+            return Ok(());
+        }
+
+        debug!("{}: no addressable zones; writing a single color", led.display());
+        fs::write(led.join(COLOR_ATTR), format_hex(color))?;
+        return Ok(());
+    }
+
+    // Broadcast a single color to every zone; otherwise the caller must
+    // supply exactly one color per zone.
+    let per_zone: Vec<(u8, u8, u8)> = if colors.len() == 1 {
+        vec![color; zones]
+    } else {
+        colors.to_vec()
+    };
+
+    if per_zone.len() != zones {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("keyboard has {} zones, but {} colors were given", zones, per_zone.len())
+        ));
+    }
+
+    let intensities = per_zone.iter()
+        .flat_map(|&(r, g, b)| vec![r, g, b])
+        .map(|channel| channel.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    fs::write(led.join(MULTI_INTENSITY_ATTR), intensities)?;
+
+    // `color` is a best-effort mirror for readers of that attribute; not
+    // every multi-zone keyboard exposes it alongside `multi_intensity`.
+    let _ = fs::write(led.join(COLOR_ATTR), format_hex(per_zone[0]));
+
+    Ok(())
+}
+
+fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let color = color.trim_start_matches('#');
+
+    if color.len() != 6 {
+        return None;
+    }
+
+    let r = u8::from_str_radix(&color[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&color[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&color[4..6], 16).ok()?;
+
+    Some((r, g, b))
+}
+
+fn format_hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
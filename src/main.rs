@@ -4,6 +4,8 @@ extern crate dbus;
 extern crate fern;
 extern crate intel_pstate as pstate;
 extern crate itertools;
+#[macro_use]
+extern crate lazy_static;
 extern crate libc;
 #[macro_use]
 extern crate log;
@@ -18,20 +20,27 @@ extern crate sysfs_class;
 
 use log::LevelFilter;
 use std::process;
+use std::str;
 
 use clap::{Arg, App, AppSettings, SubCommand};
+mod backlight;
+mod charge;
 mod client;
 mod config;
 mod daemon;
+mod discovery;
 mod disks;
 mod fan;
 mod graphics;
 mod hotplug;
 mod kernel_parameters;
+mod keyboard_led;
 mod logging;
 mod modprobe;
 mod module;
 mod pci;
+mod power_profiles_daemon;
+mod scripts;
 mod snd;
 mod util;
 mod wifi;
@@ -42,6 +51,10 @@ pub static DBUS_NAME: &'static str = "com.system76.PowerDaemon";
 pub static DBUS_PATH: &'static str = "/com/system76/PowerDaemon";
 pub static DBUS_IFACE: &'static str = "com.system76.PowerDaemon";
 
+// `daemon::daemon()` additionally serves `power_profiles_daemon::DBUS_IFACE`
+// from the same event loop, for compatibility with power-profiles-daemon
+// clients (GNOME Settings, `powerprofilesctl`, etc).
+
 pub trait Power {
     fn performance(&mut self) -> Result<(), String>;
     fn balanced(&mut self) -> Result<(), String>;
@@ -53,6 +66,12 @@ pub trait Power {
 
     fn set_fan_curve(&mut self, profile: &str) -> Result<(), String>;
 
+    fn get_charge_thresholds(&mut self) -> Result<(u8, u8), String>;
+    fn set_charge_thresholds(&mut self, thresholds: (u8, u8)) -> Result<(), String>;
+
+    fn get_keyboard_color(&mut self) -> Result<String, String>;
+    fn set_keyboard_color(&mut self, color: &str) -> Result<(), String>;
+
     fn get_graphics(&mut self) -> Result<String, String>;
     fn set_graphics(&mut self, vendor: &str) -> Result<(), String>;
     fn get_graphics_power(&mut self) -> Result<bool, String>;
@@ -67,6 +86,24 @@ pub (crate) fn err_str<E: ::std::fmt::Display>(err: E) -> String {
     format!("{}", err)
 }
 
+/// Builds a `ConfigOverrides` from the `profile` subcommand's matches, for
+/// `client::client` to layer over the loaded `Config` before applying it.
+pub (crate) fn overrides_from_matches(matches: &clap::ArgMatches<'_>) -> config::ConfigOverrides {
+    fn value<T: str::FromStr>(matches: &clap::ArgMatches<'_>, name: &str) -> Option<T> {
+        matches.value_of(name).and_then(|value| value.parse().ok())
+    }
+
+    config::ConfigOverrides {
+        pstate_min: value(matches, "pstate-min"),
+        pstate_max: value(matches, "pstate-max"),
+        pstate_turbo: value(matches, "pstate-turbo"),
+        keyboard_brightness: value(matches, "keyboard-brightness"),
+        screen_brightness: value(matches, "screen-brightness"),
+        charge_start: value(matches, "charge-start"),
+        charge_end: value(matches, "charge-end"),
+    }
+}
+
 fn main() {
     let matches = App::new("system76-power")
         .about("Utility for managing graphics and power profiles")
@@ -103,7 +140,10 @@ fn main() {
         //         .requires("value"))
         // )
         .subcommand(SubCommand::with_name("config")
-            .about("Display and verify the system76-power configuration"))
+            .about("Display and verify the system76-power configuration")
+            .long_about("Display and verify the system76-power configuration.\n\n \
+                Exits non-zero, printing each problem found with its TOML\n \
+                location, if the configuration fails validation."))
         .subcommand(SubCommand::with_name("daemon")
             .about("Runs the program in daemon mode")
             .long_about("Registers a new DBUS service and starts an event loop\
@@ -116,6 +156,25 @@ fn main() {
             .about("Set a fan curve profile. Default is 'standard'")
             .arg(Arg::with_name("profile").required(true))
         )
+        .subcommand(SubCommand::with_name("charge-thresholds")
+            .about("Query or set the battery charge thresholds")
+            .long_about("Query or set the battery charge thresholds.\n\n \
+                - If no arguments are provided, the current thresholds will be queried\n \
+                - Otherwise, the given thresholds will be set, if supported by the hardware")
+            .arg(Arg::with_name("start")
+                .help("the battery percentage to resume charging at")
+                .requires("end"))
+            .arg(Arg::with_name("end")
+                .help("the battery percentage to stop charging at"))
+        )
+        .subcommand(SubCommand::with_name("keyboard-color")
+            .about("Query or set the keyboard backlight color")
+            .long_about("Query or set the keyboard backlight color.\n\n \
+                - If no argument is provided, the current color(s) will be queried\n \
+                - Otherwise, a single hex color, or a comma-separated list of one per zone, will be set")
+            .arg(Arg::with_name("color")
+                .help("a hex color (e.g. 'ff0000'), or a comma-separated list of one per zone"))
+        )
         .subcommand(SubCommand::with_name("profile")
             .about("Query or set the power profile")
             .long_about("Queries or sets the power profile.\n\n \
@@ -128,6 +187,35 @@ fn main() {
                 .help("list available power profiles")
                 .long("list")
                 .short("l"))
+            .arg(Arg::with_name("pstate-min")
+                .help("overrides the pstate minimum performance percentage")
+                .long("pstate-min")
+                .takes_value(true))
+            .arg(Arg::with_name("pstate-max")
+                .help("overrides the pstate maximum performance percentage")
+                .long("pstate-max")
+                .takes_value(true))
+            .arg(Arg::with_name("pstate-turbo")
+                .help("overrides whether pstate turbo boost is enabled")
+                .long("pstate-turbo")
+                .takes_value(true)
+                .possible_values(&["true", "false"]))
+            .arg(Arg::with_name("keyboard-brightness")
+                .help("overrides the keyboard backlight brightness percentage")
+                .long("keyboard-brightness")
+                .takes_value(true))
+            .arg(Arg::with_name("screen-brightness")
+                .help("overrides the screen backlight brightness percentage")
+                .long("screen-brightness")
+                .takes_value(true))
+            .arg(Arg::with_name("charge-start")
+                .help("overrides the battery charge start threshold")
+                .long("charge-start")
+                .takes_value(true))
+            .arg(Arg::with_name("charge-end")
+                .help("overrides the battery charge end threshold")
+                .long("charge-end")
+                .takes_value(true))
         )
         .subcommand(SubCommand::with_name("graphics")
             .about("Query or set the graphics mode")
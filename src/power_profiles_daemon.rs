@@ -0,0 +1,152 @@
+//! A `net.hadess.PowerProfiles`-compatible D-Bus object.
+//!
+//! GNOME Settings, the top-bar battery menu, and `powerprofilesctl` all speak
+//! this interface rather than our own `com.system76.PowerDaemon`. Serving it
+//! alongside our native interface, from the same event loop, lets
+//! system76-power act as a drop-in replacement for power-profiles-daemon
+//! without any changes on the desktop side.
+//!
+//! This module only models the interface's state machine (active profile,
+//! stacked holds); it is registered onto the daemon's `dbus::tree` alongside
+//! `DBUS_IFACE` by `daemon::daemon()`.
+
+use std::sync::Mutex;
+
+use config::{Config, Profile};
+
+pub const DBUS_NAME: &str = "net.hadess.PowerProfiles";
+pub const DBUS_PATH: &str = "/net/hadess/PowerProfiles";
+pub const DBUS_IFACE: &str = "net.hadess.PowerProfiles";
+
+/// The three profile names power-profiles-daemon clients expect.
+pub const PROFILE_BATTERY: &str = "power-saver";
+pub const PROFILE_BALANCED: &str = "balanced";
+pub const PROFILE_PERFORMANCE: &str = "performance";
+
+/// Maps one of our profile names to the equivalent power-profiles-daemon
+/// name, defaulting to `balanced` for anything we don't recognize (which
+/// includes any user-defined profile beyond the three built-ins, since
+/// power-profiles-daemon has no concept of custom profiles).
+pub fn to_hadess_profile(profile: &str) -> &'static str {
+    match profile {
+        "battery" => PROFILE_BATTERY,
+        "performance" => PROFILE_PERFORMANCE,
+        _ => PROFILE_BALANCED,
+    }
+}
+
+/// Maps a power-profiles-daemon profile name back to one of ours.
+pub fn from_hadess_profile(profile: &str) -> &'static str {
+    match profile {
+        PROFILE_BATTERY => "battery",
+        PROFILE_PERFORMANCE => "performance",
+        _ => "balanced",
+    }
+}
+
+/// Ranks a hold's profile so the effective hold can be chosen by how
+/// performance-preserving it is, matching power-profiles-daemon: a
+/// `performance` hold always wins over a `power-saver` hold, regardless of
+/// which was requested first or most recently.
+fn profile_rank(profile: &str) -> u8 {
+    match profile {
+        PROFILE_PERFORMANCE => 2,
+        PROFILE_BATTERY => 0,
+        _ => 1,
+    }
+}
+
+/// A single outstanding `HoldProfile` request.
+#[derive(Clone, Debug)]
+pub struct ProfileHold {
+    pub cookie: u32,
+    pub profile: String,
+    pub reason: String,
+    pub application_id: String,
+}
+
+/// Tracks the stack of active holds and which profile was configured before
+/// any hold took over, so it can be restored once the stack empties.
+#[derive(Default)]
+pub struct PowerProfiles {
+    holds: Mutex<HoldState>,
+}
+
+#[derive(Default)]
+struct HoldState {
+    next_cookie: u32,
+    stack: Vec<ProfileHold>,
+    restore_to: Option<String>,
+}
+
+impl PowerProfiles {
+    pub fn new() -> Self {
+        Self { holds: Mutex::new(HoldState::default()) }
+    }
+
+    /// Pushes a new hold onto the stack, remembering the profile to restore
+    /// to if this is the first hold. Returns the cookie identifying it.
+    ///
+    /// The highest-priority hold's profile is the one that should actually
+    /// be applied, per `profile_rank` (not simply the most recent); the
+    /// daemon driving this struct is responsible for calling `set_profile`
+    /// with it.
+    pub fn hold_profile(&self, current_profile: &str, profile: String, reason: String, application_id: String) -> u32 {
+        let mut state = self.holds.lock().unwrap();
+
+        if state.stack.is_empty() {
+            state.restore_to = Some(current_profile.to_string());
+        }
+
+        state.next_cookie = state.next_cookie.wrapping_add(1);
+        let cookie = state.next_cookie;
+
+        state.stack.push(ProfileHold { cookie, profile, reason, application_id });
+
+        cookie
+    }
+
+    /// Removes the hold with the given cookie. Returns the profile that
+    /// should now be applied: either the remaining hold with the highest
+    /// `profile_rank`, or the profile that was active before the first
+    /// hold, if the stack is now empty.
+    pub fn release_profile(&self, cookie: u32) -> Result<Option<String>, String> {
+        let mut state = self.holds.lock().unwrap();
+
+        let position = state.stack.iter().position(|hold| hold.cookie == cookie)
+            .ok_or_else(|| format!("no hold with cookie {}", cookie))?;
+
+        state.stack.remove(position);
+
+        if let Some(hold) = highest_priority(&state.stack) {
+            Ok(Some(hold.profile.clone()))
+        } else {
+            Ok(state.restore_to.take())
+        }
+    }
+
+    /// The hold that should currently be in effect: the one with the
+    /// highest `profile_rank`, breaking ties in favor of whichever was
+    /// requested most recently.
+    pub fn active_hold(&self) -> Option<ProfileHold> {
+        highest_priority(&self.holds.lock().unwrap().stack).cloned()
+    }
+
+    pub fn is_held(&self) -> bool {
+        !self.holds.lock().unwrap().stack.is_empty()
+    }
+}
+
+/// The stack entry with the highest `profile_rank`. `Iterator::max_by_key`
+/// returns the last of any tied elements, so ties break in favor of
+/// whichever hold was pushed most recently.
+fn highest_priority(stack: &[ProfileHold]) -> Option<&ProfileHold> {
+    stack.iter().max_by_key(|hold| profile_rank(&hold.profile))
+}
+
+/// Persists `hadess_profile` (a power-profiles-daemon profile name such as
+/// `power-saver`), translated to our naming, as the last-applied profile so
+/// both D-Bus interfaces observe the same state after a restart.
+pub fn persist_last_profile(config: &mut Config, hadess_profile: &str) {
+    config.defaults.last_profile = Profile(from_hadess_profile(hadess_profile).to_string());
+}
@@ -0,0 +1,83 @@
+//! Runs `ConfigProfile::on_load`/`on_unload` transition hooks.
+//!
+//! On every profile change, `daemon::daemon()` is expected to call
+//! `transition()` with the outgoing profile's `on_unload` and the incoming
+//! profile's `on_load`, in that order, so the outgoing profile can undo its
+//! own side effects before the new one applies its own.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A hung script must not be able to wedge the D-Bus event loop.
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs `outgoing`'s `on_unload` (if any) followed by `incoming`'s `on_load`
+/// (if any) on a dedicated thread, each given `PROFILE` and `POWER_SOURCE`
+/// environment variables.
+///
+/// Returns immediately; a slow or hung script only stalls this thread, not
+/// the D-Bus event loop that calls `transition`. Errors from either script
+/// are logged once both have finished, rather than aborting the transition
+/// partway through.
+pub fn transition(
+    outgoing: Option<(String, PathBuf)>,
+    incoming: Option<(String, PathBuf)>,
+    power_source: String,
+) {
+    thread::spawn(move || {
+        let mut errors = Vec::new();
+
+        if let Some((profile, script)) = outgoing {
+            if let Err(why) = run(&script, &profile, &power_source) {
+                errors.push(format!("on_unload for '{}' failed: {}", profile, why));
+            }
+        }
+
+        if let Some((profile, script)) = incoming {
+            if let Err(why) = run(&script, &profile, &power_source) {
+                errors.push(format!("on_load for '{}' failed: {}", profile, why));
+            }
+        }
+
+        for error in &errors {
+            error!("{}", error);
+        }
+    });
+}
+
+fn run(script: &Path, profile: &str, power_source: &str) -> Result<(), String> {
+    let mut child = Command::new(script)
+        .env("PROFILE", profile)
+        .env("POWER_SOURCE", power_source)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|why| format!("failed to spawn {}: {}", script.display(), why))?;
+
+    let start = Instant::now();
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return if status.success() {
+                    Ok(())
+                } else {
+                    Err(format!("{} exited with {}", script.display(), status))
+                };
+            }
+            Ok(None) => {
+                if start.elapsed() >= SCRIPT_TIMEOUT {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!("{} timed out after {:?}", script.display(), SCRIPT_TIMEOUT));
+                }
+
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(why) => return Err(format!("failed to wait on {}: {}", script.display(), why)),
+        }
+    }
+}